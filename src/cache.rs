@@ -0,0 +1,112 @@
+//! On-disk result cache keyed by image content and preprocessing options.
+//!
+//! Repeated OCR of the same image (with the same resize/encoding settings) is
+//! served straight from `~/.cache/google-lens-ocr/<hash>` without touching the
+//! network. The hash mixes the decoded RGBA bytes with the [`Preprocessor`]
+//! configuration so changing e.g. the encoding busts the cache.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::Hasher;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use image::RgbaImage;
+
+use crate::preprocess::Preprocessor;
+use crate::OcrResponse;
+
+/// The cache directory, or `None` when no cache dir can be determined.
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|d| d.join("google-lens-ocr"))
+}
+
+/// Remove the entire cache directory, if it exists. `override_dir` takes
+/// precedence over the platform default so `clear-cache` wipes the same
+/// location that [`Cache::new`] writes to.
+pub fn clear(override_dir: Option<PathBuf>) -> anyhow::Result<()> {
+    if let Some(dir) = override_dir.or_else(cache_dir) {
+        if dir.exists() {
+            fs::remove_dir_all(&dir).context("Could not clear cache directory")?;
+        }
+    }
+    Ok(())
+}
+
+/// A best-effort, filesystem-backed cache of OCR responses.
+#[derive(Debug, Clone, Default)]
+pub struct Cache {
+    dir: Option<PathBuf>,
+}
+
+impl Cache {
+    /// Create a cache; `enabled` being false yields an inert cache that never
+    /// hits or stores anything. `override_dir` takes precedence over the
+    /// platform default cache location.
+    pub fn new(enabled: bool, override_dir: Option<PathBuf>) -> Self {
+        Self {
+            dir: enabled.then(|| override_dir.or_else(cache_dir)).flatten(),
+        }
+    }
+
+    /// The content hash for an image under the given preprocessing options.
+    pub fn key(&self, img: &RgbaImage, preprocessor: &Preprocessor) -> String {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(img.as_raw());
+        preprocessor.hash_into(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(key))
+    }
+
+    /// Fetch a cached response, ignoring any read or parse errors.
+    pub fn get(&self, key: &str) -> Option<OcrResponse> {
+        let path = self.path(key)?;
+        let data = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    /// Store a response, silently ignoring failures — a cache miss is never
+    /// worth aborting an otherwise successful OCR over.
+    pub fn put(&self, key: &str, response: &OcrResponse) {
+        let Some(path) = self.path(key) else { return };
+        let Some(dir) = self.dir.as_ref() else { return };
+        if fs::create_dir_all(dir).is_ok() {
+            if let Ok(data) = serde_json::to_string(response) {
+                let _ = fs::write(path, data);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocess::{Encoding, Preprocessor};
+    use image::RgbaImage;
+
+    #[test]
+    fn key_is_stable_and_content_sensitive() {
+        let cache = Cache::default();
+        let pre = Preprocessor::default();
+        let img = RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 4]));
+        let other = RgbaImage::from_pixel(2, 2, image::Rgba([9, 9, 9, 9]));
+
+        assert_eq!(cache.key(&img, &pre), cache.key(&img, &pre));
+        assert_ne!(cache.key(&img, &pre), cache.key(&other, &pre));
+    }
+
+    #[test]
+    fn key_depends_on_preprocessing() {
+        let cache = Cache::default();
+        let img = RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 4]));
+        let png = Preprocessor::default();
+        let jpeg = Preprocessor {
+            encoding: Encoding::Jpeg(80),
+            ..Preprocessor::default()
+        };
+        assert_ne!(cache.key(&img, &png), cache.key(&img, &jpeg));
+    }
+}
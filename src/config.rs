@@ -0,0 +1,91 @@
+//! User configuration loaded from a TOML file in the platform config dir.
+//!
+//! Every request detail Google can rotate out from under us — the User-Agent,
+//! the `SOCS` cookie, the upload endpoint, the multipart boundary, the pixel
+//! cap — lives here with a built-in default, so it can be overridden without
+//! recompiling. CLI flags still win over the file, which wins over defaults.
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::preprocess::DEFAULT_PIXEL_CAP;
+use crate::OutputFormat;
+
+fn default_user_agent() -> String {
+    "Mozilla/5.0 (Linux; Android 13; RMX3771) AppleWebKit/537.36 (KHTML, like Gecko) \
+     Chrome/121.0.6167.144 Mobile Safari/537.36"
+        .to_owned()
+}
+
+fn default_cookie() -> String {
+    "SOCS=CAESEwgDEgk0ODE3Nzk3MjQaAmVuIAEaBgiA_LyaBg".to_owned()
+}
+
+fn default_endpoint() -> String {
+    "https://lens.google.com/v3/upload".to_owned()
+}
+
+fn default_boundary() -> String {
+    "ZPJQvnUMIqajI5LbS8cc5w".to_owned()
+}
+
+fn default_pixel_cap() -> u32 {
+    DEFAULT_PIXEL_CAP
+}
+
+/// Size of the batch thread pool. Kept small so we don't trip Google's rate
+/// limiting by hammering the upload endpoint.
+fn default_threads() -> usize {
+    4
+}
+
+/// Typed configuration with a default for every key.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub user_agent: String,
+    pub cookie: String,
+    pub endpoint: String,
+    pub boundary: String,
+    pub pixel_cap: u32,
+    pub format: OutputFormat,
+    pub cache_dir: Option<PathBuf>,
+    pub threads: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            user_agent: default_user_agent(),
+            cookie: default_cookie(),
+            endpoint: default_endpoint(),
+            boundary: default_boundary(),
+            pixel_cap: default_pixel_cap(),
+            format: OutputFormat::default(),
+            cache_dir: None,
+            threads: default_threads(),
+        }
+    }
+}
+
+impl Config {
+    /// The config file path, `<config dir>/google-lens-ocr/config.toml`.
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("google-lens-ocr").join("config.toml"))
+    }
+
+    /// Load the config file, falling back to defaults when it is absent.
+    pub fn load() -> anyhow::Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("Could not parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Could not read {}", path.display())),
+        }
+    }
+}
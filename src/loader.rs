@@ -0,0 +1,209 @@
+//! Input format dispatch: everything becomes an [`RgbaImage`] before upload.
+//!
+//! Plain raster formats go straight through `image::open`, while vector and
+//! document inputs (SVG, PDF, HEIF/HEIC) are rasterised first. The accepted
+//! extension set is exposed so directory/batch mode filters consistently.
+
+use std::path::Path;
+
+use anyhow::Context;
+use image::RgbaImage;
+
+/// Default DPI used when rasterising vector/document inputs.
+pub const DEFAULT_DPI: f32 = 96.0;
+
+/// Extensions understood by [`load`], raster and rasterisable alike.
+pub const ACCEPTED_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "bmp", "tiff", "tif", "webp", // raster
+    "svg", // vector
+    "pdf", // document
+    "heic", "heif", // HEIF
+];
+
+/// Returns whether `path`'s extension is one we can load.
+pub fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| ACCEPTED_EXTENSIONS.iter().any(|s| e.eq_ignore_ascii_case(s)))
+        .unwrap_or(false)
+}
+
+/// The broad input categories we dispatch on.
+enum Kind {
+    Raster,
+    Svg,
+    Pdf,
+    Heif,
+}
+
+/// Classify `path` by extension first, falling back to magic bytes.
+fn classify(path: &Path, bytes: &[u8]) -> anyhow::Result<Kind> {
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        match ext.to_ascii_lowercase().as_str() {
+            "svg" => return Ok(Kind::Svg),
+            "pdf" => return Ok(Kind::Pdf),
+            "heic" | "heif" => return Ok(Kind::Heif),
+            _ => {}
+        }
+    }
+
+    match bytes {
+        [0x25, 0x50, 0x44, 0x46, ..] => Ok(Kind::Pdf), // %PDF
+        // ISO-BMFF `ftyp` box with a HEIF brand.
+        [_, _, _, _, b'f', b't', b'y', b'p', b'h', b'e', ..] => Ok(Kind::Heif),
+        // Scan the whole buffer: an SVG root can sit after a long XML prolog,
+        // DOCTYPE or comment, so a fixed window would misclassify it as raster.
+        b if b.windows(5).any(|w| {
+            w[..4].eq_ignore_ascii_case(b"<svg")
+                && matches!(w[4], b' ' | b'>' | b'/' | b'\t' | b'\n' | b'\r')
+        }) =>
+        {
+            Ok(Kind::Svg)
+        }
+        _ => Ok(Kind::Raster),
+    }
+}
+
+/// Build an error naming the format we could not handle, as required so the
+/// caller learns *what* was rejected rather than just that decoding failed.
+fn unsupported(path: &Path) -> anyhow::Error {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => anyhow::anyhow!("unsupported format: .{ext} ({})", path.display()),
+        None => anyhow::anyhow!("unsupported format: {}", path.display()),
+    }
+}
+
+/// Load any supported input at `path`, rasterising to RGBA as needed.
+pub fn load(path: &Path, dpi: f32) -> anyhow::Result<RgbaImage> {
+    let bytes = std::fs::read(path).context("Could not read input file")?;
+    match classify(path, &bytes)? {
+        Kind::Raster => image::load_from_memory(&bytes)
+            .map(|img| img.into_rgba8())
+            .map_err(|_| unsupported(path)),
+        Kind::Svg => rasterize_svg(&bytes, dpi),
+        Kind::Pdf => rasterize_pdf(&bytes, dpi),
+        Kind::Heif => decode_heif(&bytes),
+    }
+}
+
+/// Render an SVG document to RGBA at the requested DPI.
+fn rasterize_svg(bytes: &[u8], dpi: f32) -> anyhow::Result<RgbaImage> {
+    // usvg resolves every length to CSS pixels (96 per inch); scale from there.
+    let options = usvg::Options::default();
+    let tree = usvg::Tree::from_data(bytes, &options).context("Could not parse SVG")?;
+    let scale = dpi / 96.0;
+    let size = tree
+        .size()
+        .to_int_size()
+        .scale_by(scale)
+        .context("SVG has zero-sized canvas")?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .context("SVG has zero-sized canvas")?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+    RgbaImage::from_raw(size.width(), size.height(), pixmap.take())
+        .context("Could not build image from rendered SVG")
+}
+
+/// Render the first page of a PDF to RGBA at the requested DPI.
+fn rasterize_pdf(bytes: &[u8], dpi: f32) -> anyhow::Result<RgbaImage> {
+    let pdfium = pdfium_render::prelude::Pdfium::default();
+    let document = pdfium
+        .load_pdf_from_byte_slice(bytes, None)
+        .context("Could not load PDF")?;
+    let page = document
+        .pages()
+        .first()
+        .context("PDF has no pages")?;
+    let config = pdfium_render::prelude::PdfRenderConfig::new().scale_page_by_factor(dpi / 72.0);
+    let image = page
+        .render_with_config(&config)
+        .context("Could not render PDF page")?
+        .as_image()
+        .into_rgba8();
+    Ok(image)
+}
+
+/// Decode a HEIF/HEIC image to RGBA.
+fn decode_heif(bytes: &[u8]) -> anyhow::Result<RgbaImage> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let lib = LibHeif::new();
+    let ctx = HeifContext::read_from_bytes(bytes).context("Could not read HEIF")?;
+    let handle = ctx.primary_image_handle().context("HEIF has no image")?;
+    let image = lib
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+        .context("Could not decode HEIF")?;
+    let planes = image.planes();
+    let plane = planes.interleaved.context("HEIF plane missing")?;
+    let (width, height) = (plane.width, plane.height);
+
+    // Drop any row stride padding the decoder may have inserted.
+    let mut data = Vec::with_capacity((width * height * 4) as usize);
+    for row in plane.data.chunks_exact(plane.stride) {
+        data.extend_from_slice(&row[..(width * 4) as usize]);
+    }
+    RgbaImage::from_raw(width, height, data).context("Could not build image from HEIF")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn classifies_by_extension() {
+        let cases = [
+            ("a.svg", Kind::Svg),
+            ("a.PDF", Kind::Pdf),
+            ("a.heic", Kind::Heif),
+            ("a.HEIF", Kind::Heif),
+            ("a.png", Kind::Raster),
+        ];
+        for (name, expected) in cases {
+            let kind = classify(Path::new(name), &[]).unwrap();
+            assert!(std::mem::discriminant(&kind) == std::mem::discriminant(&expected));
+        }
+    }
+
+    #[test]
+    fn classifies_by_magic_bytes() {
+        // Extensionless inputs fall back to content sniffing.
+        let pdf = classify(Path::new("noext"), b"%PDF-1.7\n").unwrap();
+        assert!(matches!(pdf, Kind::Pdf));
+
+        let svg = classify(Path::new("noext"), b"<?xml?><svg xmlns=''></svg>").unwrap();
+        assert!(matches!(svg, Kind::Svg));
+
+        let heif = classify(Path::new("noext"), b"\0\0\0\x18ftypheic").unwrap();
+        assert!(matches!(heif, Kind::Heif));
+
+        let raster = classify(Path::new("noext"), b"\x89PNG\r\n").unwrap();
+        assert!(matches!(raster, Kind::Raster));
+    }
+
+    #[test]
+    fn detects_svg_after_long_prolog() {
+        // A long XML prolog/DOCTYPE/comment pushes the root past any fixed
+        // window; the whole buffer must still be scanned.
+        let mut payload = Vec::new();
+        payload.extend_from_slice(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        payload.extend_from_slice(b"<!-- ");
+        payload.extend_from_slice(&vec![b'x'; 1024]);
+        payload.extend_from_slice(b" -->\n<svg xmlns=''></svg>");
+        assert!(matches!(classify(Path::new("noext"), &payload).unwrap(), Kind::Svg));
+    }
+
+    #[test]
+    fn accepts_known_extensions_case_insensitively() {
+        assert!(is_supported(Path::new("photo.JPG")));
+        assert!(is_supported(Path::new("doc.pdf")));
+        assert!(!is_supported(Path::new("notes.txt")));
+        assert!(!is_supported(Path::new("noextension")));
+    }
+
+    #[test]
+    fn unsupported_names_the_extension() {
+        let err = unsupported(Path::new("file.xyz")).to_string();
+        assert!(err.contains(".xyz"), "{err}");
+    }
+}
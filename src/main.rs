@@ -1,28 +1,166 @@
-use std::{env::args_os, io::Cursor, path::PathBuf, sync::OnceLock, time::SystemTime};
+use std::{env::args_os, path::PathBuf, sync::OnceLock, time::SystemTime};
+
+mod cache;
+mod config;
+mod loader;
+mod preprocess;
 
 use anyhow::{bail, Context};
 use arboard::Clipboard;
+use cache::Cache;
+use config::Config;
 use image::RgbaImage;
+use preprocess::{parse_encoding, parse_filter, Preprocessor, ResizeOp};
+use rayon::iter::{ParallelBridge, ParallelIterator};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use walkdir::WalkDir;
+
+/// How OCR results are rendered to the caller.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => bail!("unknown output format {other:?}, expected text or json"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum Command {
     Normal(PathBuf),
     Clipboard(Option<PathBuf>),
+    Batch(PathBuf),
+    ClearCache,
+}
+
+/// Run-time options parsed from the command line, shared across every command.
+struct Options {
+    format: OutputFormat,
+    preprocessor: Preprocessor,
+    cache: Cache,
+    dpi: f32,
+    threads: usize,
+    config: Config,
 }
 
 impl Command {
-    fn new() -> anyhow::Result<Self> {
+    fn new() -> anyhow::Result<(Self, Options)> {
+        // Config file provides the defaults; CLI flags below override them.
+        let config = Config::load()?;
+        let mut format = config.format;
+        let mut preprocessor = Preprocessor {
+            resize: ResizeOp::CapPixels(config.pixel_cap),
+            ..Preprocessor::default()
+        };
+        let mut no_cache = false;
+        let mut dpi = loader::DEFAULT_DPI;
+        let mut threads = config.threads;
+        let mut positional = Vec::new();
         let mut args = args_os();
         args.next(); // skip argv[0]
-        match args.next() {
-            Some(s) if s == "clipboard" => Ok(Self::Clipboard(args.next().map(PathBuf::from))),
-            Some(s) => Ok(Self::Normal(PathBuf::from(s))),
-            None => anyhow::bail!("missing file to use OCR with"),
+        while let Some(arg) = args.next() {
+            let mut value = || -> anyhow::Result<String> {
+                Ok(args
+                    .next()
+                    .with_context(|| format!("{} requires an argument", arg.to_string_lossy()))?
+                    .to_string_lossy()
+                    .into_owned())
+            };
+            match arg.to_str() {
+                Some("--format") => format = OutputFormat::parse(&value()?)?,
+                Some("--cap") => preprocessor.resize = ResizeOp::CapPixels(value()?.parse()?),
+                Some("--scale") => preprocessor.resize = parse_dimensions(&value()?)?,
+                Some("--fit") => preprocessor.resize = parse_fit(&value()?)?,
+                Some("--fit-width") => preprocessor.resize = ResizeOp::FitWidth(value()?.parse()?),
+                Some("--fit-height") => preprocessor.resize = ResizeOp::FitHeight(value()?.parse()?),
+                Some("--filter") => preprocessor.filter = parse_filter(&value()?)?,
+                Some("--encode") => preprocessor.encoding = parse_encoding(&value()?)?,
+                Some("--no-cache") => no_cache = true,
+                Some("--dpi") => dpi = value()?.parse().context("invalid DPI")?,
+                Some("--threads") => {
+                    threads = value()?.parse().context("invalid thread count")?;
+                    if threads == 0 {
+                        bail!("--threads must be at least 1");
+                    }
+                }
+                _ => positional.push(arg),
+            }
         }
+
+        let mut positional = positional.into_iter();
+        let command = match positional.next() {
+            Some(s) if s == "clipboard" => Self::Clipboard(positional.next().map(PathBuf::from)),
+            Some(s) if s == "clear-cache" => Self::ClearCache,
+            Some(s) if s == "batch" => match positional.next() {
+                Some(dir) => Self::Batch(PathBuf::from(dir)),
+                None => bail!("missing directory to batch OCR"),
+            },
+            Some(s) => Self::Normal(PathBuf::from(s)),
+            None => bail!("missing file to use OCR with"),
+        };
+        let cache = Cache::new(!no_cache, config.cache_dir.clone());
+        let options = Options {
+            format,
+            preprocessor,
+            cache,
+            dpi,
+            threads,
+            config,
+        };
+        Ok((command, options))
     }
 }
 
+/// Parse a `WxH` pair into a [`ResizeOp::Scale`].
+fn parse_dimensions(s: &str) -> anyhow::Result<ResizeOp> {
+    let (w, h) = s.split_once('x').context("expected WIDTHxHEIGHT")?;
+    Ok(ResizeOp::Scale(w.parse()?, h.parse()?))
+}
+
+/// Parse a `WxH` pair into a [`ResizeOp::Fit`] box.
+fn parse_fit(s: &str) -> anyhow::Result<ResizeOp> {
+    let (w, h) = s.split_once('x').context("expected WIDTHxHEIGHT")?;
+    Ok(ResizeOp::Fit(w.parse()?, h.parse()?))
+}
+
+fn run_batch(dir: PathBuf, options: &Options) -> anyhow::Result<()> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .build()
+        .context("Could not build batch thread pool")?;
+
+    pool.install(|| {
+        WalkDir::new(&dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file() && loader::is_supported(entry.path()))
+            .par_bridge()
+            .for_each(|entry| {
+                let path = entry.path();
+                let result = loader::load(path, options.dpi)
+                    .and_then(|img| run_ocr(img, &options.preprocessor, &options.cache, &options.config))
+                    .and_then(|r| r.render(options.format));
+                match result {
+                    Ok(text) => println!("{}:\n{text}\n", path.display()),
+                    Err(e) => eprintln!("{}: {e:#}", path.display()),
+                }
+            });
+    });
+
+    Ok(())
+}
+
 fn get_timestamp_ms() -> u128 {
     SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
@@ -37,40 +175,200 @@ fn get_regex() -> &'static Regex {
     })
 }
 
-const BOUNDARY: &str = "ZPJQvnUMIqajI5LbS8cc5w";
-
-fn maybe_resize_image(img: RgbaImage) -> RgbaImage {
-    if img.width() * img.height() > 3_000_000 {
-        let aspect_ratio = img.width() as f64 / img.height() as f64;
-        let nwidth = ((3_000_000f64 * aspect_ratio).sqrt()) as u32;
-        let nheight = (nwidth as f64 / aspect_ratio) as u32;
-        image::imageops::resize(&img, nwidth, nheight, image::imageops::FilterType::Lanczos3)
-    } else {
-        img
-    }
-}
-
-fn create_multipart_form(filename: &str, img: &[u8]) -> Vec<u8> {
+fn create_multipart_form(filename: &str, mime: &str, boundary: &str, img: &[u8]) -> Vec<u8> {
     let mut buffer = Vec::with_capacity(img.len() + 500);
     buffer.extend_from_slice(b"--");
-    buffer.extend_from_slice(BOUNDARY.as_bytes());
+    buffer.extend_from_slice(boundary.as_bytes());
+    buffer.extend_from_slice(b"\r\n");
+    buffer.extend_from_slice(b"Content-Type: ");
+    buffer.extend_from_slice(mime.as_bytes());
     buffer.extend_from_slice(b"\r\n");
-    buffer.extend_from_slice(b"Content-Type: image/png\r\n");
     buffer.extend_from_slice(b"Content-Disposition: form-data; name=\"encoded_image\"; ");
     buffer.extend_from_slice(b"filename=\"");
     buffer.extend_from_slice(filename.as_bytes());
     buffer.extend_from_slice(b"\"\r\n\r\n");
     buffer.extend_from_slice(img);
     buffer.extend_from_slice(b"\r\n--");
-    buffer.extend_from_slice(BOUNDARY.as_bytes());
+    buffer.extend_from_slice(boundary.as_bytes());
     buffer.extend_from_slice(b"--\r\n");
     buffer
 }
 
-fn load_image(path: PathBuf) -> anyhow::Result<RgbaImage> {
-    Ok(image::open(path)
-        .context("Could not open image")?
-        .into_rgba8())
+/// Axis-aligned bounding box in normalised image coordinates (0..1).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BoundingBox {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl BoundingBox {
+    /// Convert a Lens geometry quad to a top-left box.
+    ///
+    /// Lens does not document the layout; we assume `[center_x, center_y,
+    /// width, height, ..]` normalised to `0..1`, matching what the
+    /// `chrome-lens-ocr` reference observes. Treat the result as best-effort —
+    /// if Google reorders the quad, the numbers here shift with it.
+    fn from_quad(quad: &[f64]) -> Option<Self> {
+        let [cx, cy, width, height, ..] = quad else {
+            return None;
+        };
+        Some(Self {
+            x: cx - width / 2.0,
+            y: cy - height / 2.0,
+            width: *width,
+            height: *height,
+        })
+    }
+}
+
+/// The smallest box enclosing every word box in `words`, if any.
+fn union_bbox(words: &[OcrWord]) -> Option<BoundingBox> {
+    let mut boxes = words.iter().filter_map(|w| w.bbox.as_ref());
+    let first = boxes.next()?;
+    let (mut x0, mut y0) = (first.x, first.y);
+    let (mut x1, mut y1) = (first.x + first.width, first.y + first.height);
+    for b in boxes {
+        x0 = x0.min(b.x);
+        y0 = y0.min(b.y);
+        x1 = x1.max(b.x + b.width);
+        y1 = y1.max(b.y + b.height);
+    }
+    Some(BoundingBox {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OcrWord {
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bbox: Option<BoundingBox>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OcrLine {
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bbox: Option<BoundingBox>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    words: Vec<OcrWord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrResponse {
+    lines: Vec<OcrLine>,
+}
+
+impl OcrResponse {
+    /// Newline-joined line text, matching the original flat output.
+    fn to_text(&self) -> String {
+        let mut buffer = String::new();
+        for line in &self.lines {
+            buffer.push_str(&line.text);
+            buffer.push('\n');
+        }
+        buffer.truncate(buffer.trim_end().len());
+        buffer
+    }
+
+    fn render(&self, format: OutputFormat) -> anyhow::Result<String> {
+        match format {
+            OutputFormat::Text => Ok(self.to_text()),
+            OutputFormat::Json => {
+                Ok(serde_json::to_string_pretty(self).context("Could not serialize OCR response")?)
+            }
+        }
+    }
+}
+
+/// Pull a `[f64; 4+]` quad out of the first numeric sub-array of `value`.
+///
+/// The quad layout is assumed to be `[center_x, center_y, width, height, ..]`
+/// normalised to `0..1`; Lens does not document it, so geometry is best-effort
+/// (see [`BoundingBox::from_quad`]).
+fn find_quad(value: &Value) -> Option<BoundingBox> {
+    let array = value.as_array()?;
+    array.iter().find_map(|elem| {
+        let nums: Vec<f64> = elem.as_array()?.iter().filter_map(Value::as_f64).collect();
+        if nums.len() >= 4 {
+            BoundingBox::from_quad(&nums)
+        } else {
+            None
+        }
+    })
+}
+
+/// Parse a single word node `[text, .., quad]` carrying geometry.
+fn word_from_node(value: &Value) -> Option<OcrWord> {
+    let array = value.as_array()?;
+    let text = array.first()?.as_str()?;
+    if text.is_empty() {
+        return None;
+    }
+    Some(OcrWord {
+        text: text.to_owned(),
+        bbox: Some(find_quad(value)?),
+        language: None,
+    })
+}
+
+/// Recursively collect word groups — one per text line — from the geometry
+/// subtree beside `/data/3/4/0/0`. Words that are siblings in the response
+/// belong to the same line, so grouping preserves Lens' own structure instead
+/// of guessing from the text. This is best-effort: the sibling layout is not
+/// documented, so a response that nests geometry differently yields no groups
+/// and the flat text is returned without boxes.
+fn collect_word_groups(value: &Value, out: &mut Vec<Vec<OcrWord>>) {
+    let Some(array) = value.as_array() else { return };
+    let group: Vec<OcrWord> = array.iter().filter_map(word_from_node).collect();
+    if !group.is_empty() {
+        out.push(group);
+        return; // this array is a line of words; don't descend into them
+    }
+    for elem in array {
+        collect_word_groups(elem, out);
+    }
+}
+
+/// Assemble lines from the reliable flat text, attaching each line's word
+/// group by document order (Lens emits both flat text and geometry groups
+/// top-to-bottom). Association is positional, never by string matching, so a
+/// word is never stolen by a line that merely contains its characters.
+fn build_lines(text_lines: &[Value], groups: Vec<Vec<OcrWord>>, language: Option<&str>) -> Vec<OcrLine> {
+    let mut groups = groups.into_iter();
+    text_lines
+        .iter()
+        .filter_map(|value| {
+            let text = value.as_str()?.to_owned();
+            let mut words = groups.next().unwrap_or_default();
+            for word in &mut words {
+                word.language = language.map(str::to_owned);
+            }
+            Some(OcrLine {
+                bbox: union_bbox(&words),
+                text,
+                words,
+                language: language.map(str::to_owned),
+            })
+        })
+        .collect()
+}
+
+/// Best-effort detected language code (`/data/3/3`), if Lens reported one.
+fn detect_language(root: &Value) -> Option<String> {
+    root.pointer("/data/3/3")
+        .and_then(Value::as_str)
+        .filter(|s| (2..=5).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(str::to_owned)
 }
 
 fn load_image_from_clipboard(clipboard: &mut Clipboard) -> anyhow::Result<RgbaImage> {
@@ -79,18 +377,32 @@ fn load_image_from_clipboard(clipboard: &mut Clipboard) -> anyhow::Result<RgbaIm
         .context("buffer was not big enough somehow")
 }
 
-fn run_ocr(img: RgbaImage) -> anyhow::Result<String> {
-    let img = maybe_resize_image(img);
-    let mut bytes = Vec::new();
-    img.write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)?;
+fn run_ocr(
+    img: RgbaImage,
+    preprocessor: &Preprocessor,
+    cache: &Cache,
+    config: &Config,
+) -> anyhow::Result<OcrResponse> {
+    let key = cache.key(&img, preprocessor);
+    if let Some(cached) = cache.get(&key) {
+        return Ok(cached);
+    }
+
+    let bytes = preprocessor.prepare(img)?;
 
     let ts = get_timestamp_ms();
-    let url = format!("https://lens.google.com/v3/upload?stcs={ts}");
-    let body = create_multipart_form(&format!("{ts}.png"), &bytes);
+    let url = format!("{}?stcs={ts}", config.endpoint);
+    let ext = preprocessor.encoding.extension();
+    let body = create_multipart_form(
+        &format!("{ts}.{ext}"),
+        preprocessor.encoding.mime(),
+        &config.boundary,
+        &bytes,
+    );
     let resp = ureq::post(&url)
-        .set("User-Agent", "Mozilla/5.0 (Linux; Android 13; RMX3771) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.6167.144 Mobile Safari/537.36")
-        .set("Cookie", "SOCS=CAESEwgDEgk0ODE3Nzk3MjQaAmVuIAEaBgiA_LyaBg")
-        .set("Content-Type", &format!("multipart/form-data; boundary={BOUNDARY}"))
+        .set("User-Agent", &config.user_agent)
+        .set("Cookie", &config.cookie)
+        .set("Content-Type", &format!("multipart/form-data; boundary={}", config.boundary))
         .send_bytes(&body)?;
 
     if resp.status() != 200 {
@@ -109,41 +421,148 @@ fn run_ocr(img: RgbaImage) -> anyhow::Result<String> {
         .and_then(|s| s.as_array())
         .context("Could not find OCR data")?;
 
-    let Some(data) = data.first().and_then(|s| s.as_array()) else {
-        return Ok(String::new());
+    let Some(text_lines) = data.first().and_then(|s| s.as_array()) else {
+        return Ok(OcrResponse { lines: Vec::new() });
     };
 
-    let mut buffer = String::new();
-    for elem in data {
-        let Some(s) = elem.as_str() else {
-            continue;
-        };
-        buffer.push_str(s);
-        buffer.push('\n');
+    // Recover per-word geometry from the arrays sitting beside the flat text,
+    // grouped into lines in document order.
+    let mut groups = Vec::new();
+    for sibling in data.iter().skip(1) {
+        collect_word_groups(sibling, &mut groups);
     }
-    buffer.truncate(buffer.trim_end().len());
-    Ok(buffer)
+
+    let language = detect_language(&value);
+    let lines = build_lines(text_lines, groups, language.as_deref());
+    let response = OcrResponse { lines };
+    cache.put(&key, &response);
+    Ok(response)
 }
 
 fn main() -> anyhow::Result<()> {
-    let command = Command::new()?;
+    let (command, options) = Command::new()?;
     match command {
         Command::Normal(path) => {
-            let image = load_image(path)?;
-            let result = run_ocr(image)?;
-            println!("{result}\n");
+            let image = loader::load(&path, options.dpi)?;
+            let result = run_ocr(image, &options.preprocessor, &options.cache, &options.config)?;
+            println!("{}\n", result.render(options.format)?);
         }
         Command::Clipboard(path) => {
             let mut clipboard = Clipboard::new().context("Could not open clipboard")?;
             let image = match path {
-                Some(path) => load_image(path)?,
+                Some(path) => loader::load(&path, options.dpi)?,
                 None => load_image_from_clipboard(&mut clipboard)?,
             };
-            let result = run_ocr(image)?;
+            let result = run_ocr(image, &options.preprocessor, &options.cache, &options.config)?;
             clipboard
-                .set_text(result)
+                .set_text(result.render(options.format)?)
                 .context("Could not set clipboard contents")?;
         }
+        Command::Batch(dir) => run_batch(dir, &options)?,
+        Command::ClearCache => cache::clear(options.config.cache_dir.clone())?,
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    // These exercise the extraction *logic* against the assumed (undocumented)
+    // Lens layout described on `from_quad`/`collect_word_groups`; they do not
+    // prove the assumption against a live payload, which we cannot capture
+    // offline. Geometry output is documented as best-effort accordingly.
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn quad_center_converts_to_top_left() {
+        let bbox = BoundingBox::from_quad(&[0.5, 0.5, 0.2, 0.1]).unwrap();
+        assert!((bbox.x - 0.4).abs() < 1e-9);
+        assert!((bbox.y - 0.45).abs() < 1e-9);
+        assert_eq!(bbox.width, 0.2);
+        assert_eq!(bbox.height, 0.1);
+    }
+
+    #[test]
+    fn from_quad_needs_four_numbers() {
+        assert!(BoundingBox::from_quad(&[0.1, 0.2, 0.3]).is_none());
+    }
+
+    #[test]
+    fn collect_word_groups_preserves_sibling_lines() {
+        // Two lines, each a sibling array of `[text, quad]` word nodes.
+        let node = json!([
+            [["hello", [0.1, 0.1, 0.05, 0.02]], ["world", [0.3, 0.1, 0.06, 0.02]]],
+            [["again", [0.1, 0.5, 0.05, 0.02]]]
+        ]);
+        let mut groups = Vec::new();
+        collect_word_groups(&node, &mut groups);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), 2);
+        assert_eq!(groups[0][0].text, "hello");
+        assert!(groups[0][0].bbox.is_some());
+        assert_eq!(groups[1][0].text, "again");
+    }
+
+    #[test]
+    fn build_lines_associates_groups_by_order_not_substring() {
+        // "tomato" must not steal the "to" word — association is positional.
+        let text_lines = vec![json!("tomato"), json!("to")];
+        let groups = vec![
+            vec![OcrWord {
+                text: "tomato".into(),
+                bbox: BoundingBox::from_quad(&[0.1, 0.1, 0.1, 0.1]),
+                language: None,
+            }],
+            vec![OcrWord {
+                text: "to".into(),
+                bbox: BoundingBox::from_quad(&[0.4, 0.5, 0.1, 0.1]),
+                language: None,
+            }],
+        ];
+        let lines = build_lines(&text_lines, groups, Some("en"));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].words.len(), 1);
+        assert_eq!(lines[0].words[0].text, "tomato");
+        assert_eq!(lines[1].words.len(), 1);
+        assert_eq!(lines[1].words[0].text, "to");
+        assert_eq!(lines[0].language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn build_lines_unions_word_boxes() {
+        let text_lines = vec![json!("hello world")];
+        let groups = vec![vec![
+            OcrWord {
+                text: "hello".into(),
+                bbox: BoundingBox::from_quad(&[0.1, 0.1, 0.1, 0.1]),
+                language: None,
+            },
+            OcrWord {
+                text: "world".into(),
+                bbox: BoundingBox::from_quad(&[0.4, 0.1, 0.1, 0.1]),
+                language: None,
+            },
+        ]];
+        let lines = build_lines(&text_lines, groups, None);
+        let bbox = lines[0].bbox.as_ref().unwrap();
+        assert!((bbox.x - 0.05).abs() < 1e-9);
+        assert!((bbox.width - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn build_lines_without_geometry_yields_bare_text() {
+        let text_lines = vec![json!("just text")];
+        let lines = build_lines(&text_lines, Vec::new(), None);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].words.is_empty());
+        assert!(lines[0].bbox.is_none());
+    }
+
+    #[test]
+    fn detect_language_rejects_non_codes() {
+        let ok = json!({"data": [0, 1, 2, [0, 1, 2, "en"]]});
+        let bad = json!({"data": [0, 1, 2, [0, 1, 2, "not-a-code"]]});
+        assert_eq!(detect_language(&ok).as_deref(), Some("en"));
+        assert_eq!(detect_language(&bad), None);
+    }
+}